@@ -7,7 +7,7 @@ fn bench_swaps(c: &mut Criterion) {
     c.bench_function("swap_updates", |b| {
         b.iter(|| {
             Python::with_gil(|py| {
-                let mut eng = FeatureEngine::new(py).unwrap();
+                let mut eng = FeatureEngine::new(py, 2).unwrap();
                 for i in 0..1_000_000 {
                     eng.push_swap_event(1.0, i);
                 }
@@ -5,6 +5,11 @@ pub struct FeatureVec {
     pub data: [f32; 256],
     pub means: [f64; 256],
     pub vars: [f64; 256],
+    /// Timestamp of the last swap event seen by this instance, used by the
+    /// inter-arrival-time functor. Lives here (not on the functor) so each
+    /// `FeatureVec` — one per engine — keeps its own history instead of
+    /// sharing state with every other engine through the functor registry.
+    pub last_swap_ts: Option<i64>,
 }
 
 impl FeatureVec {
@@ -14,6 +19,7 @@ impl FeatureVec {
             data: [0.0; 256],
             means: [0.0; 256],
             vars: [0.0; 256],
+            last_swap_ts: None,
         }
     }
 
@@ -29,8 +35,44 @@ impl FeatureVec {
         out.copy_from_slice(&self.data);
     }
 
+    /// Applies the EWMA Welford recurrence only to the lanes an event
+    /// actually touched. A single event only ever lights up a handful of
+    /// the 256 channels (three for a Swap, two for a Liquidity event), so
+    /// decaying every other lane too would drag unrelated features' running
+    /// mean/var toward zero on events that never produced a value for them.
     #[inline(always)]
-    pub fn update(&mut self, idx: usize, value: f64, lambda: f64) {
-        welford::update(&mut self.means[idx], &mut self.vars[idx], value, lambda);
+    pub fn update_touched(&mut self, touched: &[(usize, f64)], lambda: f64) {
+        for &(idx, value) in touched {
+            welford::update(&mut self.means[idx], &mut self.vars[idx], value, lambda);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untouched_lanes_do_not_decay() {
+        let mut fv = FeatureVec::new();
+        fv.means[0] = 0.5;
+        fv.means[1] = 0.5;
+
+        fv.update_touched(&[(0, 1.0)], 0.995);
+
+        assert_ne!(fv.means[0], 0.5, "touched lane should have decayed toward 1.0");
+        assert_eq!(fv.means[1], 0.5, "untouched lane must be left exactly alone");
+    }
+
+    #[test]
+    fn empty_touched_is_a_no_op() {
+        let mut fv = FeatureVec::new();
+        fv.means[3] = 1.25;
+        fv.vars[3] = 2.0;
+
+        fv.update_touched(&[], 0.995);
+
+        assert_eq!(fv.means[3], 1.25);
+        assert_eq!(fv.vars[3], 2.0);
     }
 }
@@ -1,13 +1,19 @@
 use crate::event::Event;
 use crate::features::FeatureVec;
-use crate::welford;
-use std::sync::atomic::{AtomicI64, Ordering};
 
 pub trait Functor: Send + Sync {
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event);
+    /// Computes this functor's (feature index, raw value) contribution for
+    /// `evt`, or `None` if the event doesn't apply. Callers collect every
+    /// matched functor's contribution for an event and decay only those
+    /// lanes via `FeatureVec::update_touched`, instead of updating indices
+    /// one at a time or decaying every lane regardless of whether the event
+    /// touched it. `fv` is passed through so a functor can read/update any
+    /// per-instance history it needs (e.g. the last swap timestamp) — never
+    /// its own fields, since functors are shared singletons across every
+    /// `FeatureEngine` via `REGISTRY`.
+    fn contribute(&self, fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)>;
 }
 
-const LAMBDA: f64 = 0.995;
 const IDX_LIQ_DELTA_ABS: usize = 0;
 const IDX_LIQ_DELTA_RATIO: usize = 1;
 const IDX_OF_SIGNED_VOL: usize = 64;
@@ -17,11 +23,11 @@ const IDX_OF_IA_TIME_MS: usize = 66;
 pub struct LiqDeltaAbs;
 impl Functor for LiqDeltaAbs {
     #[inline(always)]
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event) {
+    fn contribute(&self, _fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)> {
         if let Event::Liquidity { delta, .. } = evt {
-            let abs = delta.abs();
-            fv.data[IDX_LIQ_DELTA_ABS] += abs as f32;
-            welford::update(&mut fv.means[IDX_LIQ_DELTA_ABS], &mut fv.vars[IDX_LIQ_DELTA_ABS], abs, LAMBDA);
+            Some((IDX_LIQ_DELTA_ABS, delta.abs()))
+        } else {
+            None
         }
     }
 }
@@ -29,11 +35,12 @@ impl Functor for LiqDeltaAbs {
 pub struct LiqDeltaRatio;
 impl Functor for LiqDeltaRatio {
     #[inline(always)]
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event) {
+    fn contribute(&self, _fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)> {
         if let Event::Liquidity { delta, prev } = evt {
             let ratio = if prev.abs() > f64::EPSILON { delta / prev } else { 0.0 };
-            fv.data[IDX_LIQ_DELTA_RATIO] += ratio as f32;
-            welford::update(&mut fv.means[IDX_LIQ_DELTA_RATIO], &mut fv.vars[IDX_LIQ_DELTA_RATIO], ratio, LAMBDA);
+            Some((IDX_LIQ_DELTA_RATIO, ratio))
+        } else {
+            None
         }
     }
 }
@@ -41,10 +48,11 @@ impl Functor for LiqDeltaRatio {
 pub struct OfSignedVolume;
 impl Functor for OfSignedVolume {
     #[inline(always)]
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event) {
+    fn contribute(&self, _fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)> {
         if let Event::Swap { amount, .. } = evt {
-            fv.data[IDX_OF_SIGNED_VOL] += *amount as f32;
-            welford::update(&mut fv.means[IDX_OF_SIGNED_VOL], &mut fv.vars[IDX_OF_SIGNED_VOL], *amount, LAMBDA);
+            Some((IDX_OF_SIGNED_VOL, *amount))
+        } else {
+            None
         }
     }
 }
@@ -52,26 +60,28 @@ impl Functor for OfSignedVolume {
 pub struct OfTradeCount;
 impl Functor for OfTradeCount {
     #[inline(always)]
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event) {
+    fn contribute(&self, _fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)> {
         if matches!(evt, Event::Swap { .. }) {
-            fv.data[IDX_OF_TRADE_COUNT] += 1.0;
-            welford::update(&mut fv.means[IDX_OF_TRADE_COUNT], &mut fv.vars[IDX_OF_TRADE_COUNT], 1.0, LAMBDA);
+            Some((IDX_OF_TRADE_COUNT, 1.0))
+        } else {
+            None
         }
     }
 }
 
-#[derive(Default)]
-pub struct OfIaTimeMs {
-    last: AtomicI64,
-}
+pub struct OfIaTimeMs;
 impl Functor for OfIaTimeMs {
     #[inline(always)]
-    fn apply(&self, fv: &mut FeatureVec, evt: &Event) {
+    fn contribute(&self, fv: &mut FeatureVec, evt: &Event) -> Option<(usize, f64)> {
         if let Event::Swap { timestamp_ms, .. } = evt {
-            let last = self.last.swap(*timestamp_ms, Ordering::SeqCst);
-            let dt = if last == 0 { 0.0 } else { (*timestamp_ms - last) as f64 };
-            fv.data[IDX_OF_IA_TIME_MS] = dt as f32;
-            welford::update(&mut fv.means[IDX_OF_IA_TIME_MS], &mut fv.vars[IDX_OF_IA_TIME_MS], dt, LAMBDA);
+            let dt = match fv.last_swap_ts {
+                Some(last) => (*timestamp_ms - last) as f64,
+                None => 0.0,
+            };
+            fv.last_swap_ts = Some(*timestamp_ms);
+            Some((IDX_OF_IA_TIME_MS, dt))
+        } else {
+            None
         }
     }
 }
@@ -87,10 +97,15 @@ pub static REGISTRY: Lazy<HashMap<&'static str, Arc<dyn Functor>>> = Lazy::new(|
     m.insert("liq_pool_delta_ratio", Arc::new(LiqDeltaRatio));
     m.insert("of_signed_volume", Arc::new(OfSignedVolume));
     m.insert("of_trade_count", Arc::new(OfTradeCount));
-    m.insert("of_ia_time_ms", Arc::new(OfIaTimeMs::default()));
+    m.insert("of_ia_time_ms", Arc::new(OfIaTimeMs));
     m
 });
 
 // helper arrays mapping event types to functor keys
 pub static LIQ_KEYS: &[&str] = &["liq_pool_delta_abs", "liq_pool_delta_ratio"];
 pub static SWAP_KEYS: &[&str] = &["of_signed_volume", "of_trade_count", "of_ia_time_ms"];
+
+/// Upper bound on how many functors can match a single event, sized to the
+/// longer of `SWAP_KEYS`/`LIQ_KEYS`. `dispatch` uses this to size a
+/// fixed-size stack buffer instead of heap-allocating one per event.
+pub const MAX_KEYS_PER_EVENT: usize = 3;
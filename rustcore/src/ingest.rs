@@ -0,0 +1,128 @@
+use crate::event::Event;
+use crate::ParsedEvent;
+use crossbeam_channel::{unbounded, Receiver};
+use std::thread;
+use tungstenite::Message;
+
+/// A source of raw Solana program log lines. Implementations may poll an
+/// RPC endpoint or stream notifications from a persistent subscription;
+/// either way the ingestion loop only ever needs `next_batch`.
+pub trait LogSource: Send {
+    /// Blocks until at least one log line is available and returns every
+    /// line received since the last call. An empty batch means the source
+    /// has closed and the caller should stop polling it.
+    fn next_batch(&mut self) -> Vec<String>;
+}
+
+/// Streams `logsSubscribe` notifications for a single program id over a
+/// websocket connection, forwarding raw log lines to a background channel
+/// so `next_batch` never blocks on network I/O directly.
+pub struct WsLogSource {
+    rx: Receiver<String>,
+}
+
+impl WsLogSource {
+    /// Opens the websocket and sends the `logsSubscribe` request, blocking
+    /// for the RPC's subscription-confirmation reply before returning — so a
+    /// refused connection or a rejected subscribe call surfaces here as an
+    /// `Err`, not as a source that connects fine and then never delivers
+    /// anything. Once subscribed, a background thread keeps reading messages
+    /// off the socket and forwarding each log line they carry to `rx`; `next_batch` never
+    /// touches the socket directly.
+    pub fn connect(url: &str, program_id: &str) -> Result<Self, String> {
+        let (mut socket, _response) =
+            tungstenite::connect(url).map_err(|e| format!("websocket connect failed: {e}"))?;
+
+        let subscribe = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "logsSubscribe",
+            "params": [{ "mentions": [program_id] }, { "commitment": "confirmed" }],
+        });
+        socket
+            .write_message(Message::Text(subscribe.to_string()))
+            .map_err(|e| format!("logsSubscribe send failed: {e}"))?;
+
+        // The first reply is the subscribe ack (`{"result": <subscription
+        // id>}` or `{"error": ...}`), not a log notification — wait for it
+        // so a rejected subscription fails `connect` instead of surfacing
+        // later as a garbled first batch.
+        loop {
+            match socket.read_message() {
+                Ok(Message::Text(text)) => {
+                    let ack: serde_json::Value = serde_json::from_str(&text)
+                        .map_err(|e| format!("malformed logsSubscribe reply: {e}"))?;
+                    if let Some(err) = ack.get("error") {
+                        return Err(format!("logsSubscribe rejected: {err}"));
+                    }
+                    if ack.get("result").is_some() {
+                        break;
+                    }
+                    // Anything else before the ack isn't the confirmation;
+                    // keep waiting for it.
+                }
+                Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => continue,
+                Ok(_) => continue,
+                Err(e) => return Err(format!("websocket closed before logsSubscribe ack: {e}")),
+            }
+        }
+
+        let (tx, rx) = unbounded();
+        thread::spawn(move || loop {
+            match socket.read_message() {
+                Ok(Message::Text(text)) => {
+                    // A `logsNotification` carries its log lines under
+                    // `params.result.value.logs`; forward those lines.
+                    let note: serde_json::Value = match serde_json::from_str(&text) {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    };
+                    let logs = note.pointer("/params/result/value/logs").and_then(|l| l.as_array());
+                    for line in logs.into_iter().flatten().filter_map(|l| l.as_str()) {
+                        if tx.send(line.to_string()).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => continue,
+                Ok(Message::Close(_)) | Err(_) => return,
+                Ok(_) => continue,
+            }
+        });
+
+        Ok(Self { rx })
+    }
+}
+
+impl LogSource for WsLogSource {
+    fn next_batch(&mut self) -> Vec<String> {
+        let mut batch = match self.rx.recv() {
+            Ok(line) => vec![line],
+            Err(_) => return Vec::new(),
+        };
+        while let Ok(line) = self.rx.try_recv() {
+            batch.push(line);
+        }
+        batch
+    }
+}
+
+/// Maps a parsed log line to the crate's internal [`Event`] representation,
+/// discarding kinds the feature pipeline doesn't model.
+pub fn to_event(parsed: &ParsedEvent) -> Option<Event> {
+    match parsed.kind.as_str() {
+        "swap" => Some(Event::Swap {
+            amount: parsed.amount_out - parsed.amount_in,
+            timestamp_ms: parsed.ts,
+        }),
+        // `ParsedEvent` carries pool reserves rather than an explicit
+        // delta/prev pair, so liquidity events are derived from the
+        // reserve snapshot: the imbalance between the two sides is the
+        // delta, and `reserve_a` (one side's reserve) stands in for `prev`.
+        "liquidity" => Some(Event::Liquidity {
+            delta: parsed.reserve_a - parsed.reserve_b,
+            prev: parsed.reserve_a,
+        }),
+        _ => None,
+    }
+}
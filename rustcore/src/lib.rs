@@ -4,17 +4,18 @@ use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 use serde::Deserialize;
 
+mod event;
 mod features;
+mod functors;
+mod ingest;
 mod welford;
 
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use event::Event;
 use features::FeatureVec;
-
-const LAMBDA: f64 = 0.995;
-const IDX_LIQ_DELTA_ABS: usize = 0;
-const IDX_LIQ_DELTA_RATIO: usize = 1;
-const IDX_OF_SIGNED_VOL: usize = 64;
-const IDX_OF_TRADE_COUNT: usize = 65;
-const IDX_OF_IA_TIME_MS: usize = 66;
+use ingest::LogSource;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 #[pyclass]
 
@@ -90,105 +91,264 @@ pub fn parse_log(log: &str) -> PyResult<Option<ParsedEvent>> {
     }
 }
 
+const LAMBDA: f64 = 0.995;
+
+/// Collects every functor matched for `evt` into a small stack-allocated
+/// buffer and decays only those lanes via `FeatureVec::update_touched` — the
+/// other ~253 features an event's functors didn't touch keep their running
+/// mean/var untouched, instead of being dragged toward zero by a phantom
+/// `value = 0` contribution that never happened.
+fn dispatch(fv: &mut FeatureVec, evt: &Event, keys: &[&str]) {
+    debug_assert!(keys.len() <= functors::MAX_KEYS_PER_EVENT);
+    let mut touched = [(0usize, 0.0f64); functors::MAX_KEYS_PER_EVENT];
+    let mut n = 0;
+    for key in keys {
+        if let Some(functor) = functors::REGISTRY.get(key) {
+            if let Some((idx, value)) = functor.contribute(fv, evt) {
+                fv.data[idx] += value as f32;
+                touched[n] = (idx, value);
+                n += 1;
+            }
+        }
+    }
+    fv.update_touched(&touched[..n], LAMBDA);
+}
+
+/// Owns the `FeatureVec` and drains `Event`s dispatched through the
+/// `Functor` `REGISTRY` (`SWAP_KEYS`/`LIQ_KEYS` pick which functors run for
+/// which event kind), decoupling feature computation from ingestion. On
+/// `Event::Flush` it publishes the current vector to `snapshot` and replies
+/// on `ack`, which is how `FeatureEngine::on_slot_end` blocks until the
+/// pipeline has drained everything queued ahead of the flush.
+fn run_worker(rx: Receiver<Event>, snapshot: Arc<Mutex<FeatureVec>>) {
+    let mut fv = FeatureVec::new();
+    for evt in rx {
+        match &evt {
+            Event::Swap { .. } => dispatch(&mut fv, &evt, functors::SWAP_KEYS),
+            Event::Liquidity { .. } => dispatch(&mut fv, &evt, functors::LIQ_KEYS),
+            Event::Flush { ack } => {
+                {
+                    let mut snap = snapshot.lock().unwrap();
+                    snap.data = fv.data;
+                    snap.means = fv.means;
+                    snap.vars = fv.vars;
+                }
+                fv.reset_data();
+                let _ = ack.send(());
+            }
+        }
+    }
+}
+
+/// Per-feature rolling aggregates over the last `history` slots: mean,
+/// min, max, and the mean step between consecutive slots ("slope").
+struct RollingAggregates {
+    mean: [f32; 256],
+    min: [f32; 256],
+    max: [f32; 256],
+    slope: [f32; 256],
+}
+
+/// Number of aggregate bands `RollingAggregates` produces (mean/min/max/slope).
+const AGG_BANDS: usize = 4;
+
 #[pyclass]
 
 pub struct FeatureEngine {
-    fv: FeatureVec,
-    lag1: [f32; 256],
-    lag2: [f32; 256],
+    tx: Sender<Event>,
+    /// Feature vector as of the last `Flush`; the worker only touches this
+    /// under lock at flush time, so the hot per-event path stays lock-free.
+    snapshot: Arc<Mutex<FeatureVec>>,
+    /// Ring buffer of the last `history.len()` completed slots, oldest slot
+    /// at `head`. Rotation advances `head` rather than shifting elements, so
+    /// it stays O(1) regardless of window size.
+    history: Vec<[f32; 256]>,
+    head: usize,
+    /// Number of completed slots actually written into `history` so far,
+    /// capped at `history.len()`. Lets `rolling_aggregates` tell real slots
+    /// apart from the ring's zero-initialized placeholders during warm-up.
+    filled: usize,
     /// Pre-allocated output buffer exposed to Python.
     out: Py<PyArray1<f32>>,
-    last_swap_ts: Option<i64>,
 }
 
 #[pymethods]
 impl FeatureEngine {
     #[new]
-    pub fn new(py: Python<'_>) -> PyResult<Self> {
-        // Allocate a contiguous NumPy array once; Rust mutates it in place.
-        let out = PyArray1::<f32>::zeros(py, [256 * 3], false);
+    pub fn new(py: Python<'_>, history: usize) -> PyResult<Self> {
+        if history == 0 {
+            return Err(PyValueError::new_err("history must be at least 1"));
+        }
+        // Layout: current slot, `history` lag slots, then the aggregate bands.
+        let bands = 1 + history + AGG_BANDS;
+        let out = PyArray1::<f32>::zeros(py, [256 * bands], false);
+        let (tx, rx) = unbounded();
+        let snapshot = Arc::new(Mutex::new(FeatureVec::new()));
+        thread::spawn({
+            let snapshot = Arc::clone(&snapshot);
+            move || run_worker(rx, snapshot)
+        });
         Ok(Self {
-            fv: FeatureVec::new(),
-            lag1: [0.0; 256],
-            lag2: [0.0; 256],
+            tx,
+            snapshot,
+            history: vec![[0.0; 256]; history],
+            head: 0,
+            filled: 0,
             out: out.into_py(py),
-            last_swap_ts: None,
         })
     }
 
     pub fn push_event(&mut self, evt: PyEvent) -> PyResult<()> {
-        match evt.tag.as_str() {
+        let event = match evt.tag.as_str() {
             "Liquidity" => {
                 let delta = evt.delta.ok_or_else(|| PyValueError::new_err("missing delta"))?;
                 let prev = evt.prev.ok_or_else(|| PyValueError::new_err("missing prev"))?;
-                self.apply_liquidity(delta, prev);
+                Event::Liquidity { delta, prev }
             }
             "Swap" => {
-                let amt = evt.amount.ok_or_else(|| PyValueError::new_err("missing amount"))?;
-                let ts = evt.timestamp_ms.ok_or_else(|| PyValueError::new_err("missing timestamp"))?;
-                self.apply_swap(amt, ts);
+                let amount = evt.amount.ok_or_else(|| PyValueError::new_err("missing amount"))?;
+                let timestamp_ms = evt
+                    .timestamp_ms
+                    .ok_or_else(|| PyValueError::new_err("missing timestamp"))?;
+                Event::Swap { amount, timestamp_ms }
             }
             _ => return Err(PyValueError::new_err("unknown event tag")),
-        }
+        };
+        self.tx
+            .send(event)
+            .map_err(|_| PyValueError::new_err("feature worker disconnected"))
+    }
+
+    /// Spawns a background thread that pulls raw Solana program logs from
+    /// `url` for `program_id`, parses them with `parse_log`, and feeds the
+    /// resulting events into the same worker pipeline as `push_event` —
+    /// without crossing the GIL for any event that comes from the source.
+    /// The subscription is established before this call returns, so a
+    /// transport that can't be wired up raises here instead of leaving the
+    /// caller with a source that silently never delivers anything.
+    pub fn attach_source(&mut self, url: String, program_id: String) -> PyResult<()> {
+        let mut source = ingest::WsLogSource::connect(&url, &program_id)
+            .map_err(PyValueError::new_err)?;
+        let tx = self.tx.clone();
+        thread::spawn(move || loop {
+            let batch = source.next_batch();
+            if batch.is_empty() {
+                return;
+            }
+            for line in batch {
+                if let Ok(Some(parsed)) = parse_log(&line) {
+                    if let Some(event) = ingest::to_event(&parsed) {
+                        if tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
         Ok(())
     }
 
     pub fn on_slot_end<'py>(&'py mut self, py: Python<'py>, _slot: u64) -> PyResult<&'py PyArray1<f32>> {
+        // Flush and wait for the worker's ack so the caller never observes a
+        // slot before every event queued ahead of it has been applied.
+        let (ack_tx, ack_rx) = bounded(1);
+        self.tx
+            .send(Event::Flush { ack: ack_tx })
+            .map_err(|_| PyValueError::new_err("feature worker disconnected"))?;
+        ack_rx
+            .recv()
+            .map_err(|_| PyValueError::new_err("feature worker disconnected"))?;
+
+        let current = self.snapshot.lock().unwrap().data;
+        let agg = self.rolling_aggregates();
+        let n = self.history.len();
+
         // Build output slice without allocation. The NumPy array's memory is
         // mutated in place, avoiding per-slot heap churn.
         let out = self.out.as_ref(py);
         // Safety: `out` was allocated as contiguous f32 array above and is not
         // aliased while we hold the GIL.
         let slice = unsafe { out.as_slice_mut()? };
-        slice[..256].copy_from_slice(&self.fv.data);
-        slice[256..512].copy_from_slice(&self.lag1);
-        slice[512..].copy_from_slice(&self.lag2);
+        slice[..256].copy_from_slice(&current);
+        for slot in 0..n {
+            // `lag` slots count back from the most recent prior slot (lag 1)
+            // to the oldest retained one (lag n).
+            let lag = slot + 1;
+            let idx = (self.head + n - lag) % n;
+            let base = 256 * (1 + slot);
+            slice[base..base + 256].copy_from_slice(&self.history[idx]);
+        }
+        let agg_base = 256 * (1 + n);
+        slice[agg_base..agg_base + 256].copy_from_slice(&agg.mean);
+        slice[agg_base + 256..agg_base + 512].copy_from_slice(&agg.min);
+        slice[agg_base + 512..agg_base + 768].copy_from_slice(&agg.max);
+        slice[agg_base + 768..agg_base + 1024].copy_from_slice(&agg.slope);
 
-        // Rotate lag buffers via swap to avoid copying 256 values per slot.
-        std::mem::swap(&mut self.lag2, &mut self.lag1);
-        std::mem::swap(&mut self.lag1, &mut self.fv.data);
-        self.fv.reset_data();
+        // Advance the ring by overwriting the oldest slot in place — O(1),
+        // no shifting of the other n-1 slots.
+        self.history[self.head] = current;
+        self.head = (self.head + 1) % n;
+        self.filled = (self.filled + 1).min(n);
 
         Ok(out)
     }
 
     pub fn get_stats(&self, idx: usize) -> (f64, f64) {
-        (self.fv.means[idx], self.fv.vars[idx])
+        let snapshot = self.snapshot.lock().unwrap();
+        (snapshot.means[idx], snapshot.vars[idx])
     }
 
     // Exposed for benchmarks
     pub fn push_swap_event(&mut self, amount: f64, timestamp_ms: i64) {
-        self.apply_swap(amount, timestamp_ms);
+        let _ = self.tx.send(Event::Swap { amount, timestamp_ms });
     }
 }
 
 impl FeatureEngine {
-    fn apply_liquidity(&mut self, delta: f64, prev: f64) {
-        let abs = delta.abs();
-        self.fv.data[IDX_LIQ_DELTA_ABS] += abs as f32;
-        self.fv.update(IDX_LIQ_DELTA_ABS, abs, LAMBDA);
-        let ratio = if prev.abs() > f64::EPSILON { delta / prev } else { 0.0 };
-        self.fv.data[IDX_LIQ_DELTA_RATIO] += ratio as f32;
-        self.fv.update(IDX_LIQ_DELTA_RATIO, ratio, LAMBDA);
-    }
-
-    fn apply_swap(&mut self, amount: f64, ts: i64) {
-        self.fv.data[IDX_OF_SIGNED_VOL] += amount as f32;
-        self.fv.update(IDX_OF_SIGNED_VOL, amount, LAMBDA);
-
-        self.fv.data[IDX_OF_TRADE_COUNT] += 1.0;
-        self.fv.update(IDX_OF_TRADE_COUNT, 1.0, LAMBDA);
-
-        let dt = if let Some(last) = self.last_swap_ts {
-            let delta = (ts - last) as f64;
-            self.fv.data[IDX_OF_IA_TIME_MS] = delta as f32;
-            self.last_swap_ts = Some(ts);
-            delta
-        } else {
-            self.last_swap_ts = Some(ts);
-            0.0
+    /// Computes rolling mean/min/max/slope per feature over the *populated*
+    /// part of the ring buffer, oldest slot first. Before `history.len()`
+    /// slots have actually been flushed, the ring still holds zero
+    /// placeholders for the not-yet-written ones — those are excluded so
+    /// early calls report real-but-short-window stats instead of stats
+    /// fabricated over phantom zeros. `slope` is the mean of the
+    /// consecutive differences across the window — the runtime-sized
+    /// stand-in for `array_windows`, which needs a compile-time window
+    /// length and can't take `history` as a constructor parameter.
+    fn rolling_aggregates(&self) -> RollingAggregates {
+        let n = self.history.len();
+        let m = self.filled.min(n);
+        let mut agg = RollingAggregates {
+            mean: [0.0; 256],
+            min: [0.0; 256],
+            max: [0.0; 256],
+            slope: [0.0; 256],
         };
-        self.fv.update(IDX_OF_IA_TIME_MS, dt, LAMBDA);
+        if m == 0 {
+            // Nothing has been flushed into the ring yet; report neutral
+            // zeros rather than aggregating over placeholder data.
+            return agg;
+        }
+
+        let mut column = vec![0.0f32; m];
+        for feat in 0..256 {
+            for (slot, value) in column.iter_mut().enumerate() {
+                // The `m` populated slots are the most recently written
+                // ones, ending just before `head`; this collapses to the
+                // usual `(head + slot) % n` once the ring is full (m == n).
+                let idx = (self.head + n - m + slot) % n;
+                *value = self.history[idx][feat];
+            }
+            agg.mean[feat] = column.iter().sum::<f32>() / m as f32;
+            agg.min[feat] = column.iter().copied().fold(f32::INFINITY, f32::min);
+            agg.max[feat] = column.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            if m >= 2 {
+                let (step_sum, steps) = column
+                    .windows(2)
+                    .fold((0.0f32, 0usize), |(sum, count), w| (sum + (w[1] - w[0]), count + 1));
+                agg.slope[feat] = step_sum / steps as f32;
+            }
+        }
+        agg
     }
 }
 
@@ -200,3 +360,25 @@ fn rustcore(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(parse_log, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_aggregates_ignores_unfilled_slots() {
+        Python::with_gil(|py| {
+            let mut eng = FeatureEngine::new(py, 3).unwrap();
+            // Only one slot is ever flushed against a history of 3. Feature
+            // 64 (of_signed_volume) gets a single positive observation here,
+            // so a warm-up bug that aggregates over the ring's unfilled
+            // zero placeholders would show up as `min == 0.0`.
+            eng.push_swap_event(5.0, 1);
+            eng.on_slot_end(py, 0).unwrap();
+
+            let agg = eng.rolling_aggregates();
+            assert!(agg.min[64] > 0.0, "min should reflect the one real slot, not a phantom zero");
+            assert_eq!(agg.mean[64], agg.max[64], "a single-slot window has no spread");
+        });
+    }
+}